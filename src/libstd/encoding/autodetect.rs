@@ -0,0 +1,218 @@
+// Copyright 2013 The Rust Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution and at
+// http://rust-lang.org/COPYRIGHT.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+/*!
+ * Picks a Unicode codec by sniffing a leading byte-order mark, falling back
+ * to a caller-supplied codec when none is present.
+ *
+ * Unlike the plain `utf16`/`utf32` decoders, which treat a BOM found anywhere
+ * in the stream as a transparently-consumed (or, mid-stream, a literal
+ * ZWNBSP) detail of that one encoding, this module's job is to pick *which*
+ * codec to use in the first place, so the BOM bytes it matches are never
+ * handed to the chosen decoder at all.
+ */
+
+use encoding::by_label::{DynDecoder, utf8_decoder, utf16_decoder, utf32_decoder};
+use encoding::StreamDecoder;
+use io::{Reader, ReaderUtil};
+use iterator::Iterator;
+use option::{Option, None, Some};
+use vec::OwnedVector;
+
+/// Sniffs a leading byte-order mark from `bytes` and returns a decoder for
+/// the codec it identifies, along with the number of leading bytes that were
+/// part of the BOM (and so are not part of the decoded text). If no BOM is
+/// recognized, returns `fallback` and `0`.
+///
+/// The 4-byte UTF-32 marks are checked before the 2-byte UTF-16 ones, since
+/// a UTF-32LE BOM (`FF FE 00 00`) starts with the same two bytes as a
+/// UTF-16LE BOM (`FF FE`).
+pub fn decode_auto(bytes: &[u8], fallback: ~DynDecoder) -> (~DynDecoder, uint) {
+    if bytes.len() >= 4 && bytes[0] == 0x00 && bytes[1] == 0x00 &&
+                            bytes[2] == 0xFE && bytes[3] == 0xFF {
+        return (utf32_decoder(true), 4);
+    }
+    if bytes.len() >= 4 && bytes[0] == 0xFF && bytes[1] == 0xFE &&
+                            bytes[2] == 0x00 && bytes[3] == 0x00 {
+        return (utf32_decoder(false), 4);
+    }
+    if bytes.len() >= 2 && bytes[0] == 0xFE && bytes[1] == 0xFF {
+        return (utf16_decoder(true), 2);
+    }
+    if bytes.len() >= 2 && bytes[0] == 0xFF && bytes[1] == 0xFE {
+        return (utf16_decoder(false), 2);
+    }
+    if bytes.len() >= 3 && bytes[0] == 0xEF && bytes[1] == 0xBB && bytes[2] == 0xBF {
+        return (utf8_decoder(), 3);
+    }
+    (fallback, 0)
+}
+
+// Replays any bytes sniffed-but-not-consumed-as-BOM before falling through
+// to the underlying `Reader`, so a `DynDecoder` can be driven directly off
+// of whatever `AutoStreamDecoder::sniff` peeked.
+struct PushbackReader<'self> {
+    priv pending: &'self mut ~[u8],
+    priv reader: &'self mut Reader
+}
+
+impl<'self> Iterator<u8> for PushbackReader<'self> {
+    fn next(&mut self) -> Option<u8> {
+        if !self.pending.is_empty() {
+            return Some(self.pending.shift());
+        }
+        self.reader.read_byte()
+    }
+}
+
+/// A `StreamDecoder` that sniffs a leading byte-order mark on its first call
+/// and dispatches to the codec it identifies for the rest of the stream,
+/// falling back to a caller-supplied codec when no BOM is present.
+pub struct AutoStreamDecoder {
+    priv fallback: Option<~DynDecoder>,
+    priv decoder: Option<~DynDecoder>,
+    priv pending: ~[u8]
+}
+
+impl AutoStreamDecoder {
+    pub fn new(fallback: ~DynDecoder) -> AutoStreamDecoder {
+        AutoStreamDecoder{ fallback: Some(fallback), decoder: None, pending: ~[] }
+    }
+
+    fn push_back(&mut self, b: Option<u8>) {
+        match b { Some(byte) => self.pending.push(byte), None => () }
+    }
+
+    fn fall_back(&mut self, read: &[Option<u8>]) {
+        self.decoder = Some(self.fallback.swap_unwrap());
+        for &b in read.iter() { self.push_back(b); }
+    }
+
+    fn sniff(&mut self, src: &mut Reader) {
+        let a = src.read_byte();
+        let b = src.read_byte();
+
+        if a == Some(0x00u8) && b == Some(0x00u8) {
+            let c = src.read_byte();
+            let d = src.read_byte();
+            if c == Some(0xFEu8) && d == Some(0xFFu8) {
+                self.decoder = Some(utf32_decoder(true));
+            } else {
+                self.fall_back(&[a, b, c, d]);
+            }
+            return;
+        }
+
+        if a == Some(0xFFu8) && b == Some(0xFEu8) {
+            let c = src.read_byte();
+            let d = src.read_byte();
+            if c == Some(0x00u8) && d == Some(0x00u8) {
+                self.decoder = Some(utf32_decoder(false));
+            } else {
+                self.decoder = Some(utf16_decoder(false));
+                self.push_back(c);
+                self.push_back(d);
+            }
+            return;
+        }
+
+        if a == Some(0xFEu8) && b == Some(0xFFu8) {
+            self.decoder = Some(utf16_decoder(true));
+            return;
+        }
+
+        if a == Some(0xEFu8) && b == Some(0xBBu8) {
+            let c = src.read_byte();
+            if c == Some(0xBFu8) {
+                self.decoder = Some(utf8_decoder());
+            } else {
+                self.fall_back(&[a, b, c]);
+            }
+            return;
+        }
+
+        self.fall_back(&[a, b]);
+    }
+}
+
+impl StreamDecoder for AutoStreamDecoder {
+    fn decode_step(&mut self, src: &mut Reader) -> Option<char> {
+        if self.decoder.is_none() {
+            self.sniff(src);
+        }
+        let mut source = PushbackReader{ pending: &mut self.pending, reader: src };
+        self.decoder.get_mut_ref().decode_next(&mut source as &mut Iterator<u8>)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use encoding::by_label::decoder_for_label;
+    use encoding::StreamDecoder;
+    use io::mem::MemReader;
+    use iterator::IteratorUtil;
+    use option::{None, Some};
+    use vec::{CopyableVector, ImmutableVector};
+
+    #[test]
+    fn test_decode_auto_utf16be_bom() {
+        let a = [0xFEu8, 0xFFu8, 0, 't' as u8];
+        let (mut dec, n) = decode_auto(a, decoder_for_label("utf-8").unwrap());
+        assert_eq!(n, 2);
+        assert_eq!(dec.decode_next(&mut a.slice_from(n).iter().transform(|x| *x)), Some('t'));
+    }
+
+    #[test]
+    fn test_decode_auto_utf32le_bom_not_misread_as_utf16le() {
+        let a = [0xFFu8, 0xFEu8, 0, 0, 't' as u8, 0, 0, 0];
+        let (mut dec, n) = decode_auto(a, decoder_for_label("utf-8").unwrap());
+        assert_eq!(n, 4);
+        assert_eq!(dec.decode_next(&mut a.slice_from(n).iter().transform(|x| *x)), Some('t'));
+    }
+
+    #[test]
+    fn test_decode_auto_utf8_bom() {
+        let a = [0xEFu8, 0xBBu8, 0xBFu8, 't' as u8];
+        let (mut dec, n) = decode_auto(a, decoder_for_label("windows-1252").unwrap());
+        assert_eq!(n, 3);
+        assert_eq!(dec.decode_next(&mut a.slice_from(n).iter().transform(|x| *x)), Some('t'));
+    }
+
+    #[test]
+    fn test_decode_auto_no_bom_uses_fallback() {
+        let a = [0x82u8];
+        let (mut dec, n) = decode_auto(a, decoder_for_label("windows-1252").unwrap());
+        assert_eq!(n, 0);
+        assert_eq!(dec.decode_next(&mut a.iter().transform(|x| *x)), Some('‚'));
+    }
+
+    #[test]
+    fn test_auto_stream_decoder_utf16le_bom() {
+        let bytes = [0xFFu8, 0xFEu8, 't' as u8, 0, 'e' as u8, 0];
+        let mut r = MemReader::new(bytes.to_owned());
+        let mut dec = AutoStreamDecoder::new(decoder_for_label("utf-8").unwrap());
+        assert_eq!(dec.decode_step(&mut r), Some('t'));
+        assert_eq!(dec.decode_step(&mut r), Some('e'));
+        assert_eq!(dec.decode_step(&mut r), None);
+    }
+
+    #[test]
+    fn test_auto_stream_decoder_no_bom_uses_fallback() {
+        // looks like the start of a UTF-16LE BOM but isn't one; those two
+        // bytes must be replayed as content through the fallback decoder.
+        let bytes = [0xFFu8, 'i' as u8];
+        let mut r = MemReader::new(bytes.to_owned());
+        let mut dec = AutoStreamDecoder::new(decoder_for_label("windows-1252").unwrap());
+        assert_eq!(dec.decode_step(&mut r), Some('ÿ'));
+        assert_eq!(dec.decode_step(&mut r), Some('i'));
+        assert_eq!(dec.decode_step(&mut r), None);
+    }
+}
@@ -0,0 +1,435 @@
+// Copyright 2013 The Rust Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution and at
+// http://rust-lang.org/COPYRIGHT.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+/*!
+ * Looks up a codec by the encoding label the web platform uses (an HTTP
+ * `charset` parameter, a `<meta charset>` value, etc).
+ *
+ * The `Encoder`/`Decoder` traits are generic over the iterator types they
+ * produce, so a function can't simply return "some decoder" without fixing
+ * a concrete type. `DynDecoder` is an object-safe trait that erases that
+ * type, so `decoder_for_label` can hand back one of several codecs behind
+ * a single `~DynDecoder`.
+ */
+
+use char::ReplacementChar;
+use encoding::invalid_byte;
+use encoding::{DecodeAsReplacementChar, DecodeAs, SkipInvalidByte,
+               TruncateDecoding, FailDecoding};
+use encoding::singlebyte::{windows_1252, iso_8859_2, iso_8859_15, high_table, UNMAPPED};
+use iterator::Iterator;
+use option::{Option, None, Some};
+use str;
+use vec;
+use vec::{CopyableVector, ImmutableVector};
+
+/// An object-safe counterpart to `Decoder`: decodes one character at a time
+/// from a borrowed byte iterator, so heterogeneous codecs can be stored
+/// behind a single boxed trait object.
+pub trait DynDecoder {
+    /// Decode the next character from `src`, or `None` once the stream is
+    /// exhausted.
+    fn decode_next(&mut self, src: &mut Iterator<u8>) -> Option<char>;
+}
+
+/// Look up a decoder for a web platform encoding label, after lowercasing
+/// and trimming ASCII whitespace as the WHATWG Encoding Standard specifies.
+/// For the UTF-8 labels and `"utf-16"`, a leading BOM is sniffed and
+/// stripped; `"utf-16"` additionally uses the BOM to pick between little-
+/// and big-endian, defaulting to little-endian when none is present. The
+/// other labels, including `"utf-16le"` and `"utf-16be"`, do no BOM
+/// sniffing and decode a BOM as content. Returns `None` if the label isn't
+/// recognized.
+pub fn decoder_for_label(label: &str) -> Option<~DynDecoder> {
+    match normalize_label(label).as_slice() {
+        "utf-8" | "utf8" | "unicode-1-1-utf-8" =>
+            Some(~Utf8DynDecoder{ bom: true, pending: None } as ~DynDecoder),
+        "utf-16" =>
+            Some(~Utf16DynDecoder{ big: false, bom: true, pending: None, lead: None } as ~DynDecoder),
+        "utf-16le" =>
+            Some(~Utf16DynDecoder{ big: false, bom: false, pending: None, lead: None } as ~DynDecoder),
+        "utf-16be" =>
+            Some(~Utf16DynDecoder{ big: true, bom: false, pending: None, lead: None } as ~DynDecoder),
+        "iso-8859-1" | "latin1" | "latin-1" | "l1" | "cp819" | "ibm819" | "csisolatin1" =>
+            Some(~Latin1DynDecoder as ~DynDecoder),
+        "us-ascii" | "ascii" | "ansi_x3.4-1968" | "iso-ir-6" | "iso646-us" | "cp367" | "csascii" =>
+            Some(~AsciiDynDecoder as ~DynDecoder),
+        "windows-1252" | "cp1252" | "x-cp1252" =>
+            Some(~SingleByteDynDecoder{ table: high_table(&windows_1252) } as ~DynDecoder),
+        "iso-8859-2" | "iso8859-2" | "iso_8859-2" | "iso-ir-101" | "csisolatin2" | "l2" | "latin2" =>
+            Some(~SingleByteDynDecoder{ table: high_table(&iso_8859_2) } as ~DynDecoder),
+        "iso-8859-15" | "iso8859-15" | "csisolatin9" | "l9" =>
+            Some(~SingleByteDynDecoder{ table: high_table(&iso_8859_15) } as ~DynDecoder),
+        _ => None
+    }
+}
+
+/// Builds a UTF-8 decoder with no BOM-sniffing of its own, for callers (such
+/// as the BOM auto-detector) that have already decided on UTF-8 and consumed
+/// any BOM themselves.
+pub fn utf8_decoder() -> ~DynDecoder {
+    ~Utf8DynDecoder{ bom: false, pending: None } as ~DynDecoder
+}
+
+/// Builds a UTF-16 decoder for the given byte order, with no BOM-sniffing of
+/// its own (see `utf8_decoder`).
+pub fn utf16_decoder(big: bool) -> ~DynDecoder {
+    ~Utf16DynDecoder{ big: big, bom: false, pending: None, lead: None } as ~DynDecoder
+}
+
+/// Builds a UTF-32 decoder for the given byte order, with no BOM-sniffing of
+/// its own (see `utf8_decoder`).
+pub fn utf32_decoder(big: bool) -> ~DynDecoder {
+    ~Utf32DynDecoder{ big: big } as ~DynDecoder
+}
+
+fn is_ascii_whitespace(b: u8) -> bool {
+    b == ' ' as u8 || b == '\t' as u8 || b == '\n' as u8 || b == '\r' as u8 || b == 0x0Cu8
+}
+
+fn ascii_lower(b: u8) -> u8 {
+    if b >= 'A' as u8 && b <= 'Z' as u8 { b + 32 } else { b }
+}
+
+fn normalize_label(label: &str) -> ~str {
+    let bytes = label.as_bytes();
+    let mut lo = 0;
+    let mut hi = bytes.len();
+    while lo < hi && is_ascii_whitespace(bytes[lo]) { lo += 1; }
+    while hi > lo && is_ascii_whitespace(bytes[hi - 1]) { hi -= 1; }
+
+    let mut out = vec::with_capacity(hi - lo);
+    for i in range(lo, hi) {
+        out.push(ascii_lower(bytes[i]));
+    }
+    str::from_bytes_owned(out)
+}
+
+struct Utf8DynDecoder {
+    priv bom: bool,
+    priv pending: Option<u8>
+}
+
+impl Utf8DynDecoder {
+    fn decode_one(&mut self, src: &mut Iterator<u8>) -> Option<char> {
+        use encoding::invalid_byte::cond;
+
+        let a = if self.pending.is_some() {
+            self.pending.swap_unwrap()
+        } else {
+            match src.next() { Some(a) => a, None => return None }
+        };
+
+        let (len, mut c) =
+            if a < 0x80 { (1, a as u32) }
+            else if a >= 0xC2 && a <= 0xDF { (2, (a & 0x1F) as u32) }
+            else if a >= 0xE0 && a <= 0xEF { (3, (a & 0x0F) as u32) }
+            else if a >= 0xF0 && a <= 0xF4 { (4, (a & 0x07) as u32) }
+            else {
+                return Some(match cond.raise_default(Some(~[a]), || DecodeAsReplacementChar) {
+                    DecodeAsReplacementChar => ReplacementChar,
+                    DecodeAs(c) => c,
+                    SkipInvalidByte => return self.decode_one(src),
+                    TruncateDecoding => return None,
+                    FailDecoding => fail!("invalid byte sequence encountered")
+                });
+            };
+
+        let mut bytes = ~[a];
+        let mut i = 1;
+        while i < len {
+            let b = match src.next() {
+                Some(b) => b,
+                None => return Some(match cond.raise_default(None, || DecodeAsReplacementChar) {
+                    DecodeAsReplacementChar => ReplacementChar,
+                    DecodeAs(c) => c,
+                    SkipInvalidByte | TruncateDecoding => return None,
+                    FailDecoding => fail!("bytestream terminated unexpectedly")
+                })
+            };
+            let (lo, hi) = if i == 1 {
+                match a {
+                    0xE0 => (0xA0u8, 0xBFu8),
+                    0xED => (0x80u8, 0x9Fu8),
+                    0xF0 => (0x90u8, 0xBFu8),
+                    0xF4 => (0x80u8, 0x8Fu8),
+                    _ => (0x80u8, 0xBFu8)
+                }
+            } else { (0x80u8, 0xBFu8) };
+
+            bytes.push(b);
+            if b < lo || b > hi {
+                if b < 0x80 || (b >= 0xC2 && b <= 0xF4) { self.pending = Some(b); }
+                return Some(cond.raise_default(Some(bytes), || DecodeAsReplacementChar).to_char());
+            }
+            c = (c << 6) | (b & 0x3F) as u32;
+            i += 1;
+        }
+
+        Some(c as char)
+    }
+}
+
+impl DynDecoder for Utf8DynDecoder {
+    fn decode_next(&mut self, src: &mut Iterator<u8>) -> Option<char> {
+        if self.bom {
+            self.bom = false;
+            match self.decode_one(src) {
+                Some(c) if c as u32 == 0xFEFF => return self.decode_one(src),
+                other => return other
+            }
+        }
+        self.decode_one(src)
+    }
+}
+
+struct Utf16DynDecoder {
+    priv big: bool,
+    priv bom: bool,
+    priv pending: Option<char>,
+    priv lead: Option<u16>
+}
+
+impl DynDecoder for Utf16DynDecoder {
+    fn decode_next(&mut self, src: &mut Iterator<u8>) -> Option<char> {
+        use encoding::invalid_byte::cond;
+
+        if self.pending.is_some() {
+            return Some(self.pending.swap_unwrap());
+        }
+
+        loop {
+            let a = match src.next() { Some(a) => a, None => return None };
+            let b = match src.next() {
+                Some(b) => b,
+                None => return Some(match cond.raise_default(None, || DecodeAsReplacementChar) {
+                    DecodeAsReplacementChar => ReplacementChar,
+                    DecodeAs(c) => c,
+                    SkipInvalidByte | TruncateDecoding => return None,
+                    FailDecoding => fail!("bytestream terminated unexpectedly")
+                })
+            };
+
+            if self.bom {
+                self.bom = false;
+                if a == 0xFE && b == 0xFF { self.big = true; loop; }
+                else if a == 0xFF && b == 0xFE { self.big = false; loop; }
+            }
+
+            let c = if self.big { (a as u16 << 8) | (b as u16) } else { (b as u16 << 8) | (a as u16) };
+
+            if c >= 0xD800 && c <= 0xDBFF {
+                if self.lead.is_none() { self.lead = Some(c); loop; }
+            } else if c >= 0xDC00 && c <= 0xDFFF {
+                if self.lead.is_some() {
+                    let lead = (self.lead.swap_unwrap() as u32 - 0xD800) << 10;
+                    let trail = c as u32 - 0xDC00;
+                    return Some(((lead | trail) + 0x10000) as char);
+                }
+                return Some(cond.raise_default(None, || DecodeAsReplacementChar).to_char());
+            } else if self.lead.is_some() {
+                self.lead = None;
+                self.pending = Some(c as char);
+                return Some(cond.raise_default(None, || DecodeAsReplacementChar).to_char());
+            }
+
+            if self.lead.is_some() {
+                self.lead = None;
+                return Some(cond.raise_default(None, || DecodeAsReplacementChar).to_char());
+            }
+
+            return Some(c as char);
+        }
+    }
+}
+
+struct Utf32DynDecoder {
+    priv big: bool
+}
+
+impl DynDecoder for Utf32DynDecoder {
+    fn decode_next(&mut self, src: &mut Iterator<u8>) -> Option<char> {
+        use encoding::invalid_byte::cond;
+
+        let mut buf = [0u8, ..4];
+        let mut n = 0;
+        while n < 4 {
+            match src.next() {
+                Some(b) => { buf[n] = b; n += 1; }
+                None => break
+            }
+        }
+        if n == 0 { return None; }
+        if n < 4 {
+            return Some(cond.raise_default(Some(buf.slice(0, n).to_owned()), || DecodeAsReplacementChar).to_char());
+        }
+
+        let c = if self.big {
+            (buf[0] as u32 << 24) | (buf[1] as u32 << 16) | (buf[2] as u32 << 8) | (buf[3] as u32)
+        } else {
+            (buf[3] as u32 << 24) | (buf[2] as u32 << 16) | (buf[1] as u32 << 8) | (buf[0] as u32)
+        };
+
+        if (c >= 0xD800 && c <= 0xDFFF) || c > 0x10FFFF {
+            return Some(cond.raise_default(Some(buf.to_owned()), || DecodeAsReplacementChar).to_char());
+        }
+
+        Some(c as char)
+    }
+}
+
+struct Latin1DynDecoder;
+
+impl DynDecoder for Latin1DynDecoder {
+    fn decode_next(&mut self, src: &mut Iterator<u8>) -> Option<char> {
+        // every byte is a valid ISO-8859-1 code point
+        src.next().map(|b| b as char)
+    }
+}
+
+struct AsciiDynDecoder;
+
+impl DynDecoder for AsciiDynDecoder {
+    fn decode_next(&mut self, src: &mut Iterator<u8>) -> Option<char> {
+        use encoding::invalid_byte::cond;
+
+        let b = match src.next() { Some(b) => b, None => return None };
+        if b < 0x80 {
+            Some(b as char)
+        } else {
+            Some(cond.raise_default(Some(~[b]), || DecodeAsReplacementChar).to_char())
+        }
+    }
+}
+
+struct SingleByteDynDecoder {
+    priv table: &'static [char, ..128]
+}
+
+impl DynDecoder for SingleByteDynDecoder {
+    fn decode_next(&mut self, src: &mut Iterator<u8>) -> Option<char> {
+        use encoding::invalid_byte::cond;
+
+        let b = match src.next() { Some(b) => b, None => return None };
+        if b < 0x80 { return Some(b as char) }
+        let c = self.table[(b - 0x80) as uint];
+        if c == UNMAPPED {
+            Some(cond.raise_default(Some(~[b]), || DecodeAsReplacementChar).to_char())
+        } else {
+            Some(c)
+        }
+    }
+}
+
+// Turns an invalid_byte resolution into the char a `DynDecoder` yields in
+// place of the invalid bytes. `SkipInvalidByte` and `TruncateDecoding` are
+// treated like `DecodeAsReplacementChar`: the bytes decode as the
+// ReplacementChar and decoding continues after them.
+trait ResolutionToChar {
+    fn to_char(self) -> char;
+}
+
+impl ResolutionToChar for ::encoding::InvalidByteResolution {
+    fn to_char(self) -> char {
+        match self {
+            DecodeAsReplacementChar => ReplacementChar,
+            DecodeAs(c) => c,
+            SkipInvalidByte | TruncateDecoding => ReplacementChar,
+            FailDecoding => fail!("invalid byte sequence encountered")
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use char::ReplacementChar;
+    use iterator::IteratorUtil;
+    use option::{None, Some};
+    use vec::ImmutableVector;
+
+    fn decode_all(label: &str, bytes: &[u8]) -> ~[char] {
+        let mut dec = decoder_for_label(label).unwrap();
+        let mut iter = bytes.iter().transform(|x| *x);
+        let mut out = ~[];
+        loop {
+            match dec.decode_next(&mut iter as &mut Iterator<u8>) {
+                Some(c) => out.push(c),
+                None => break
+            }
+        }
+        out
+    }
+
+    #[test]
+    fn test_unknown_label_is_none() {
+        assert!(decoder_for_label("bogus-charset").is_none());
+    }
+
+    #[test]
+    fn test_label_is_trimmed_and_lowercased() {
+        assert_eq!(decode_all(" UTF-8 \t", ['t' as u8]), ~['t']);
+    }
+
+    #[test]
+    fn test_utf16_label_defaults_to_little_endian() {
+        let a = ['t' as u8, 0];
+        assert_eq!(decode_all("utf-16", a), ~['t']);
+        assert_eq!(decode_all("utf-16le", a), ~['t']);
+    }
+
+    #[test]
+    fn test_utf16be_label_is_big_endian() {
+        let a = [0, 't' as u8];
+        assert_eq!(decode_all("utf-16be", a), ~['t']);
+    }
+
+    #[test]
+    fn test_utf16_unpaired_high_surrogates_do_not_leak_lead() {
+        // two consecutive lead surrogates, followed by a trail surrogate
+        // that must not be paired with the stale first lead (which would
+        // wrongly combine into U+10000)
+        let a = [0xD8u8, 0x00u8, 0xD8u8, 0x00u8, 0xDCu8, 0x00u8];
+        let out = decode_all("utf-16be", a);
+        assert_eq!(out[0], ReplacementChar);
+        assert_eq!(out[1], ReplacementChar);
+    }
+
+    #[test]
+    fn test_utf16_unpaired_trail_surrogate_is_rejected() {
+        let a = [0xDCu8, 0x00u8];
+        assert_eq!(decode_all("utf-16be", a), ~[ReplacementChar]);
+    }
+
+    #[test]
+    fn test_utf8_label_strips_leading_bom() {
+        let a = [0xEFu8, 0xBBu8, 0xBFu8, 't' as u8];
+        assert_eq!(decode_all("utf-8", a), ~['t']);
+    }
+
+    #[test]
+    fn test_utf16_bare_label_sniffs_bom_and_overrides_default_order() {
+        let a = [0xFEu8, 0xFFu8, 0, 't' as u8];
+        assert_eq!(decode_all("utf-16", a), ~['t']);
+    }
+
+    #[test]
+    fn test_utf16le_label_does_not_sniff_bom() {
+        // utf-16le names an explicit byte order; a BOM is just content
+        let a = [0xFFu8, 0xFEu8, 't' as u8, 0];
+        assert_eq!(decode_all("utf-16le", a), ~[0xFEFFu32 as char, 't']);
+    }
+
+    #[test]
+    fn test_windows_1252_label() {
+        let a = [0x80u8];
+        assert_eq!(decode_all("windows-1252", a), ~['€']);
+    }
+}
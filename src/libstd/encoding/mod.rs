@@ -14,16 +14,38 @@
  * Encodings are implemented as a pair of Iterators, one that translates from u8
  * to char, and one that translate from char to u8.
  *
- * Encoding errors are handled by the invalid_byte and out_of_range conditions.
+ * Encoding errors are handled by the invalid_byte and out_of_range conditions
+ * by default. Callers that would rather not install a condition handler can
+ * use `try_decode`/`try_encode` (or the `try_decode_as`/`try_encode_as`
+ * convenience methods on `&[u8]`/`&[char]`) to get an iterator of `Result`s
+ * instead.
  */
 
+use io::{Reader, Writer};
 use iterator::Iterator;
-
-pub use encoding::utf16::{utf16, utf16le, utf16be, UTF16Encoder, UTF16Decoder};
+use result::{Result, Ok, Err};
+
+pub use encoding::utf16::{utf16, utf16le, utf16be, UTF16Encoder, UTF16Decoder,
+                          UTF16TryEncoder, UTF16TryDecoder,
+                          UTF16StreamEncoder, UTF16StreamDecoder};
+pub use encoding::utf8::{utf8, utf8_bom, UTF8Encoder, UTF8Decoder};
+pub use encoding::utf32::{utf32, utf32le, utf32be, UTF32Encoder, UTF32Decoder};
+pub use encoding::singlebyte::{single_byte, windows_1252, iso_8859_2, iso_8859_15,
+                                SingleByteEncoder, SingleByteDecoder};
+pub use encoding::by_label::{DynDecoder, decoder_for_label};
+pub use encoding::autodetect::{decode_auto, AutoStreamDecoder};
+pub use encoding::percentencode::{percent, percent_component, percent_path,
+                                   PercentEncoder, PercentDecoder};
 use iterator::{MapIterator,IteratorUtil};
 use vec::{VecIterator,ImmutableVector};
 
 mod utf16;
+mod utf8;
+mod utf32;
+mod singlebyte;
+mod by_label;
+mod autodetect;
+mod percentencode;
 
 /// Resolution options for the invalid_byte condition
 pub enum InvalidByteResolution {
@@ -84,6 +106,59 @@ pub trait Decoder<T: Iterator<u8>, U: Iterator<char>> {
     fn decode(&self, src: T) -> U;
 }
 
+/// What went wrong while decoding, recorded on a `DecodeError`.
+pub enum DecodeErrorKind {
+    /// The bytestream ended in the middle of a sequence.
+    UnexpectedEnd,
+    /// The bytes seen so far don't form a valid sequence in this encoding.
+    InvalidSequence
+}
+
+/// The error yielded by a `TryDecoder` in place of raising the invalid_byte
+/// condition. `bytes` holds the offending bytes for an `InvalidSequence`,
+/// and is `None` for an `UnexpectedEnd`.
+pub struct DecodeError {
+    kind: DecodeErrorKind,
+    bytes: Option<~[u8]>
+}
+
+/// The error yielded by a `TryEncoder` in place of raising the out_of_range
+/// condition, carrying the char that couldn't be represented.
+pub struct EncodeError {
+    c: char
+}
+
+/// A fallible counterpart to `Decoder`: yields `Result`s instead of raising
+/// the invalid_byte condition.
+pub trait TryDecoder<T: Iterator<u8>, U: Iterator<Result<char, DecodeError>>> {
+    fn try_decode(&self, src: T) -> U;
+}
+
+/// A fallible counterpart to `Encoder`: yields `Result`s instead of raising
+/// the out_of_range condition.
+pub trait TryEncoder<T: Iterator<char>, U: Iterator<Result<u8, EncodeError>>> {
+    fn try_encode(&self, src: T) -> U;
+}
+
+/// A decoder that can be driven a buffer at a time from a `Reader`, rather
+/// than over an in-memory byte iterator. Implementors hold whatever state
+/// spans a `read()` boundary (a half-consumed code unit, a dangling
+/// surrogate, a partial multi-byte sequence), so a codepoint split across
+/// two buffer refills still decodes correctly.
+pub trait StreamDecoder {
+    /// Decodes the next char from `src`, or `None` once the stream is
+    /// exhausted. May be called again after a previous call to continue
+    /// decoding where it left off.
+    fn decode_step(&mut self, src: &mut Reader) -> Option<char>;
+}
+
+/// An encoder that can be driven a char at a time directly into a `Writer`,
+/// rather than collecting into an in-memory byte iterator.
+pub trait StreamEncoder {
+    /// Encodes `c` and writes the resulting bytes to `dst`.
+    fn encode_step(&mut self, c: char, dst: &mut Writer);
+}
+
 type MapVecIter<'self, T> = MapIterator<'self, &'self T, T, VecIterator<'self, T>>;
 
 pub trait VecEncoder<T: Iterator<char>, U: Iterator<u8>, E: Encoder<T, U>> {
@@ -110,6 +185,30 @@ VecDecoder<MapVecIter<'self, u8>, U, D> for &'self [u8] {
     }
 }
 
+pub trait TryVecEncoder<T: Iterator<char>, U: Iterator<Result<u8, EncodeError>>, E: TryEncoder<T, U>> {
+    fn try_encode_as(self, enc: E) -> U;
+}
+
+impl<'self, U: Iterator<Result<u8, EncodeError>>, E: TryEncoder<MapVecIter<'self, char>, U>>
+TryVecEncoder<MapVecIter<'self, char>, U, E> for &'self [char] {
+    #[inline]
+    fn try_encode_as(self, enc: E) -> U {
+        enc.try_encode(self.iter().transform(|x|*x))
+    }
+}
+
+pub trait TryVecDecoder<T: Iterator<u8>, U: Iterator<Result<char, DecodeError>>, D: TryDecoder<T, U>> {
+    fn try_decode_as(self, enc: D) -> U;
+}
+
+impl<'self, U: Iterator<Result<char, DecodeError>>, D: TryDecoder<MapVecIter<'self, u8>, U>>
+TryVecDecoder<MapVecIter<'self, u8>, U, D> for &'self [u8] {
+    #[inline]
+    fn try_decode_as(self, enc: D) -> U {
+        enc.try_decode(self.iter().transform(|x|*x))
+    }
+}
+
 pub trait VecReencoder<T: Iterator<u8>, U: Iterator<char>, V: Iterator<u8>,
                        D: Decoder<T, U>, E: Encoder<U, V>> {
     fn reencode(self, from: D, to: E) -> V;
@@ -0,0 +1,285 @@
+// Copyright 2013 The Rust Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution and at
+// http://rust-lang.org/COPYRIGHT.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use encoding::{Encoder, Decoder};
+use encoding::{DecodeAsReplacementChar, DecodeAs, SkipInvalidByte,
+               TruncateDecoding, FailDecoding};
+use encoding::utf8::{utf8, UTF8Decoder};
+use char::ReplacementChar;
+use iterator::Iterator;
+use option::{Option, None, Some};
+use uint;
+
+#[allow(non_camel_case_types)]
+pub enum percent {
+    /// Safe set for URI components such as query or fragment values and
+    /// form fields: the unreserved characters only (`A-Z a-z 0-9 - . _ ~`).
+    percent_component,
+    /// Safe set for URI path segments: the unreserved characters plus the
+    /// `sub-delims` and `:`/`@` that RFC 3986 allows unescaped in a path.
+    percent_path,
+}
+
+impl<T: Iterator<char>> Encoder<T, PercentEncoder<T>> for percent {
+    fn encode(&self, src: T) -> PercentEncoder<T> {
+        PercentEncoder{ iter: src, safe: safe_set(self), buf: [0, ..12], lo: 0, hi: 0 }
+    }
+}
+
+impl<T: Iterator<u8>> Decoder<T, PercentDecoder<T>> for percent {
+    fn decode(&self, src: T) -> PercentDecoder<T> {
+        let unescaped = PercentUnescaper{ iter: Some(src), buf: [0, ..4], lo: 0, hi: 0 };
+        PercentDecoder{ iter: utf8.decode(unescaped) }
+    }
+}
+
+fn is_unreserved(b: u8) -> bool {
+    (b >= 'A' as u8 && b <= 'Z' as u8) ||
+    (b >= 'a' as u8 && b <= 'z' as u8) ||
+    (b >= '0' as u8 && b <= '9' as u8) ||
+    b == '-' as u8 || b == '.' as u8 || b == '_' as u8 || b == '~' as u8
+}
+
+fn is_path_safe(b: u8) -> bool {
+    is_unreserved(b) ||
+    b == '!' as u8 || b == '$' as u8 || b == '&' as u8 || b == '\'' as u8 ||
+    b == '(' as u8 || b == ')' as u8 || b == '*' as u8 || b == '+' as u8 ||
+    b == ',' as u8 || b == ';' as u8 || b == '=' as u8 ||
+    b == ':' as u8 || b == '@' as u8
+}
+
+fn safe_set(enc: &percent) -> fn(u8) -> bool {
+    match *enc {
+        percent_component => is_unreserved,
+        percent_path => is_path_safe
+    }
+}
+
+static HEX_DIGITS: [u8, ..16] =
+    ['0' as u8, '1' as u8, '2' as u8, '3' as u8, '4' as u8, '5' as u8, '6' as u8, '7' as u8,
+     '8' as u8, '9' as u8, 'A' as u8, 'B' as u8, 'C' as u8, 'D' as u8, 'E' as u8, 'F' as u8];
+
+fn hex_value(b: u8) -> Option<u8> {
+    if b >= '0' as u8 && b <= '9' as u8 { Some(b - '0' as u8) }
+    else if b >= 'a' as u8 && b <= 'f' as u8 { Some(b - 'a' as u8 + 10) }
+    else if b >= 'A' as u8 && b <= 'F' as u8 { Some(b - 'A' as u8 + 10) }
+    else { None }
+}
+
+// Encodes `c` as UTF-8 into `buf`, returning the number of bytes written.
+fn encode_utf8(c: char, buf: &mut [u8, ..4]) -> uint {
+    let c = c as u32;
+    if c < 0x80 {
+        buf[0] = c as u8;
+        1
+    } else if c < 0x800 {
+        buf[0] = 0xC0 | (c >> 6) as u8;
+        buf[1] = 0x80 | (c & 0x3F) as u8;
+        2
+    } else if c < 0x10000 {
+        buf[0] = 0xE0 | (c >> 12) as u8;
+        buf[1] = 0x80 | ((c >> 6) & 0x3F) as u8;
+        buf[2] = 0x80 | (c & 0x3F) as u8;
+        3
+    } else {
+        buf[0] = 0xF0 | (c >> 18) as u8;
+        buf[1] = 0x80 | ((c >> 12) & 0x3F) as u8;
+        buf[2] = 0x80 | ((c >> 6) & 0x3F) as u8;
+        buf[3] = 0x80 | (c & 0x3F) as u8;
+        4
+    }
+}
+
+pub struct PercentEncoder<T> {
+    priv iter: T,
+    priv safe: fn(u8) -> bool,
+    priv buf: [u8, ..12],
+    priv lo: uint,
+    priv hi: uint
+}
+
+impl<T: Iterator<char>> Iterator<u8> for PercentEncoder<T> {
+    #[inline]
+    fn next(&mut self) -> Option<u8> {
+        if self.lo == self.hi {
+            let c = match self.iter.next() { Some(c) => c, None => return None };
+
+            let mut raw = [0u8, ..4];
+            let n = encode_utf8(c, &mut raw);
+
+            self.lo = 0;
+            self.hi = 0;
+            for i in range(0, n) {
+                let b = raw[i];
+                if (self.safe)(b) {
+                    self.buf[self.hi] = b;
+                    self.hi += 1;
+                } else {
+                    self.buf[self.hi] = '%' as u8;
+                    self.buf[self.hi + 1] = HEX_DIGITS[(b >> 4) as uint];
+                    self.buf[self.hi + 2] = HEX_DIGITS[(b & 0x0F) as uint];
+                    self.hi += 3;
+                }
+            }
+        }
+        let r = Some(self.buf[self.lo]);
+        self.lo += 1;
+        r
+    }
+
+    #[inline]
+    fn size_hint(&self) -> (uint, Option<uint>) {
+        // at least one byte per char, at most four UTF-8 bytes each escaped to three
+        let (lo, hi) = self.iter.size_hint();
+        let hi = do hi.chain |x| {
+            if x > uint::max_value / 12 { None } else { Some(x * 12) }
+        };
+        (lo, hi)
+    }
+}
+
+struct PercentUnescaper<T> {
+    priv iter: Option<T>,
+    priv buf: [u8, ..4],
+    priv lo: uint,
+    priv hi: uint
+}
+
+impl<T: Iterator<u8>> PercentUnescaper<T> {
+    fn fill_buf(&mut self, c: char) {
+        let n = encode_utf8(c, &mut self.buf);
+        self.lo = 0;
+        self.hi = n;
+    }
+}
+
+impl<T: Iterator<u8>> Iterator<u8> for PercentUnescaper<T> {
+    fn next(&mut self) -> Option<u8> {
+        use encoding::invalid_byte::cond;
+
+        if self.lo == self.hi {
+            if self.iter.is_none() { return None; }
+
+            let b = match self.iter.get_mut_ref().next() {
+                Some(b) => b,
+                None => { self.iter = None; return None; }
+            };
+
+            if b == '%' as u8 {
+                let h = self.iter.get_mut_ref().next();
+                let l = match h { Some(_) => self.iter.get_mut_ref().next(), None => None };
+
+                let byte = match (h, l) {
+                    (Some(h), Some(l)) => match (hex_value(h), hex_value(l)) {
+                        (Some(hv), Some(lv)) => Some((hv << 4) | lv),
+                        _ => None
+                    },
+                    _ => None
+                };
+
+                match byte {
+                    Some(raw) => { self.buf[0] = raw; self.lo = 0; self.hi = 1; }
+                    None => {
+                        let mut bad = ~[b];
+                        h.map(|h| bad.push(h));
+                        l.map(|l| bad.push(l));
+                        match cond.raise_default(Some(bad), || DecodeAsReplacementChar) {
+                            DecodeAsReplacementChar => self.fill_buf(ReplacementChar),
+                            DecodeAs(c) => self.fill_buf(c),
+                            SkipInvalidByte => return self.next(),
+                            TruncateDecoding => { self.iter = None; return None; }
+                            FailDecoding => fail!("invalid byte sequence encountered")
+                        }
+                    }
+                }
+            } else {
+                self.buf[0] = b;
+                self.lo = 0;
+                self.hi = 1;
+            }
+        }
+
+        let r = self.buf[self.lo];
+        self.lo += 1;
+        Some(r)
+    }
+}
+
+pub struct PercentDecoder<T> {
+    priv iter: UTF8Decoder<PercentUnescaper<T>>
+}
+
+impl<T: Iterator<u8>> Iterator<char> for PercentDecoder<T> {
+    #[inline]
+    fn next(&mut self) -> Option<char> {
+        self.iter.next()
+    }
+
+    #[inline]
+    fn size_hint(&self) -> (uint, Option<uint>) {
+        self.iter.size_hint()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use char::ReplacementChar;
+    use iterator::IteratorUtil;
+
+    #[test]
+    fn test_percent_component_encode() {
+        let a = ['h', 'i', ' ', 't', 'h', 'e', 'r', 'e', '/', '?'];
+        assert_eq!(a.encode_as(percent_component).collect::<~[u8]>(),
+                   ~['h' as u8, 'i' as u8, '%' as u8, '2' as u8, '0' as u8,
+                     't' as u8, 'h' as u8, 'e' as u8, 'r' as u8, 'e' as u8,
+                     '%' as u8, '2' as u8, 'F' as u8, '%' as u8, '3' as u8, 'F' as u8]);
+    }
+
+    #[test]
+    fn test_percent_path_leaves_subdelims() {
+        let a = ['a', ':', '@', ' '];
+        assert_eq!(a.encode_as(percent_path).collect::<~[u8]>(),
+                   ~['a' as u8, ':' as u8, '@' as u8, '%' as u8, '2' as u8, '0' as u8]);
+    }
+
+    #[test]
+    fn test_percent_encode_non_ascii() {
+        let a = ['€'];
+        assert_eq!(a.encode_as(percent_component).collect::<~[u8]>(),
+                   ~['%' as u8, 'E' as u8, '2' as u8, '%' as u8, '8' as u8, '2' as u8,
+                     '%' as u8, 'A' as u8, 'C' as u8]);
+    }
+
+    #[test]
+    fn test_percent_roundtrip() {
+        let a = ['h', 'i', ' ', '€', '/'];
+        let bytes = a.encode_as(percent_component).collect::<~[u8]>();
+        assert_eq!(bytes.decode_as(percent_component).collect::<~[char]>(), a.to_owned());
+    }
+
+    #[test]
+    fn test_percent_decode_lowercase_hex() {
+        let a = ['%' as u8, '2' as u8, 'f' as u8];
+        assert_eq!(a.decode_as(percent_component).collect::<~[char]>(), ~['/']);
+    }
+
+    #[test]
+    fn test_percent_decode_bad_escape() {
+        let a = ['%' as u8, 'z' as u8, 'z' as u8];
+        assert_eq!(a.decode_as(percent_component).collect::<~[char]>(), ~[ReplacementChar]);
+    }
+
+    #[test]
+    fn test_percent_decode_truncated_escape() {
+        let a = ['%' as u8, '2' as u8];
+        assert_eq!(a.decode_as(percent_component).collect::<~[char]>(), ~[ReplacementChar]);
+    }
+}
@@ -0,0 +1,245 @@
+// Copyright 2013 The Rust Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution and at
+// http://rust-lang.org/COPYRIGHT.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use encoding::{Encoder, Decoder};
+use encoding::{DecodeAsReplacementChar, DecodeAs, SkipInvalidByte,
+               TruncateDecoding, FailDecoding};
+use encoding::{EncodeAsReplacementChar, EncodeAs, SkipOutOfRangeChar,
+               TruncateEncoding, FailEncoding};
+use char::ReplacementChar;
+use iterator::Iterator;
+use option::{Option, None, Some};
+
+/// Sentinel used in a high-half table to mark a byte with no mapping.
+pub static UNMAPPED: char = '￿';
+
+#[allow(non_camel_case_types)]
+pub enum single_byte {
+    /// Windows-1252 (the common "ANSI" superset of Latin-1)
+    windows_1252,
+    /// ISO-8859-2 (Latin-2)
+    iso_8859_2,
+    /// ISO-8859-15 (Latin-9)
+    iso_8859_15,
+}
+
+impl<T: Iterator<char>> Encoder<T, SingleByteEncoder<T>> for single_byte {
+    fn encode(&self, src: T) -> SingleByteEncoder<T> {
+        SingleByteEncoder{ iter: src, table: high_table(self) }
+    }
+}
+
+impl<T: Iterator<u8>> Decoder<T, SingleByteDecoder<T>> for single_byte {
+    fn decode(&self, src: T) -> SingleByteDecoder<T> {
+        SingleByteDecoder{ iter: src, table: high_table(self) }
+    }
+}
+
+/// The high-half lookup table backing `enc`, exposed so `by_label` can drive
+/// the same tables from its own `DynDecoder` wrappers.
+pub fn high_table(enc: &single_byte) -> &'static [char, ..128] {
+    match *enc {
+        windows_1252 => &WINDOWS_1252_HIGH,
+        iso_8859_2 => &ISO_8859_2_HIGH,
+        iso_8859_15 => &ISO_8859_15_HIGH,
+    }
+}
+
+fn encode_high(table: &'static [char, ..128], c: char) -> Option<u8> {
+    for (i, &t) in table.iter().enumerate() {
+        if t == c && t != UNMAPPED { return Some((i + 0x80) as u8) }
+    }
+    None
+}
+
+pub struct SingleByteEncoder<T> {
+    priv iter: T,
+    priv table: &'static [char, ..128]
+}
+
+impl<T: Iterator<char>> Iterator<u8> for SingleByteEncoder<T> {
+    #[inline]
+    fn next(&mut self) -> Option<u8> {
+        use encoding::out_of_range::cond;
+
+        loop {
+            let c = match self.iter.next() { Some(c) => c, None => return None };
+            if (c as u32) < 0x80 { return Some(c as u8) }
+            match encode_high(self.table, c) {
+                Some(b) => return Some(b),
+                None => ()
+            }
+            match cond.raise_default(c, || EncodeAsReplacementChar) {
+                EncodeAsReplacementChar =>
+                    return Some(encode_high(self.table, ReplacementChar).unwrap_or('?' as u8)),
+                EncodeAs(c_) => {
+                    if (c_ as u32) < 0x80 { return Some(c_ as u8) }
+                    match encode_high(self.table, c_) {
+                        Some(b) => return Some(b),
+                        None => fail!("out-of-range char 0x%x found", c_ as uint)
+                    }
+                }
+                SkipOutOfRangeChar => loop,
+                TruncateEncoding => return None,
+                FailEncoding => fail!("out-of-range char 0x%x found", c as uint)
+            }
+        }
+    }
+
+    #[inline]
+    fn size_hint(&self) -> (uint, Option<uint>) {
+        // exactly one byte per char
+        self.iter.size_hint()
+    }
+}
+
+pub struct SingleByteDecoder<T> {
+    priv iter: T,
+    priv table: &'static [char, ..128]
+}
+
+impl<T: Iterator<u8>> Iterator<char> for SingleByteDecoder<T> {
+    #[inline]
+    fn next(&mut self) -> Option<char> {
+        use encoding::invalid_byte::cond;
+
+        loop {
+            let b = match self.iter.next() { Some(b) => b, None => return None };
+            if b < 0x80 { return Some(b as char) }
+            let c = self.table[(b - 0x80) as uint];
+            if c == UNMAPPED {
+                match cond.raise_default(Some(~[b]), || DecodeAsReplacementChar) {
+                    DecodeAsReplacementChar => return Some(ReplacementChar),
+                    DecodeAs(c) => return Some(c),
+                    SkipInvalidByte => loop,
+                    TruncateDecoding => return None,
+                    FailDecoding => fail!("invalid byte sequence encountered")
+                }
+            }
+            return Some(c);
+        }
+    }
+
+    #[inline]
+    fn size_hint(&self) -> (uint, Option<uint>) {
+        // exactly one char per byte
+        self.iter.size_hint()
+    }
+}
+
+// The WHATWG windows-1252 index maps the bytes Microsoft's CP1252 leaves
+// unmapped (0x81, 0x8D, 0x8F, 0x90, 0x9D) straight through to the
+// corresponding C1 control code points, rather than leaving them undefined.
+static WINDOWS_1252_HIGH: [char, ..128] = [
+    '€', '\x81', '‚', 'ƒ', '„', '…', '†', '‡',
+    'ˆ', '‰', 'Š', '‹', 'Œ', '\x8D', 'Ž', '\x8F',
+    '\x90', '‘', '’', '“', '”', '•', '–', '—',
+    '˜', '™', 'š', '›', 'œ', '\x9D', 'ž', 'Ÿ',
+    '\xA0', '\xA1', '\xA2', '\xA3', '\xA4', '\xA5', '\xA6', '\xA7',
+    '\xA8', '\xA9', '\xAA', '\xAB', '\xAC', '\xAD', '\xAE', '\xAF',
+    '\xB0', '\xB1', '\xB2', '\xB3', '\xB4', '\xB5', '\xB6', '\xB7',
+    '\xB8', '\xB9', '\xBA', '\xBB', '\xBC', '\xBD', '\xBE', '\xBF',
+    '\xC0', '\xC1', '\xC2', '\xC3', '\xC4', '\xC5', '\xC6', '\xC7',
+    '\xC8', '\xC9', '\xCA', '\xCB', '\xCC', '\xCD', '\xCE', '\xCF',
+    '\xD0', '\xD1', '\xD2', '\xD3', '\xD4', '\xD5', '\xD6', '\xD7',
+    '\xD8', '\xD9', '\xDA', '\xDB', '\xDC', '\xDD', '\xDE', '\xDF',
+    '\xE0', '\xE1', '\xE2', '\xE3', '\xE4', '\xE5', '\xE6', '\xE7',
+    '\xE8', '\xE9', '\xEA', '\xEB', '\xEC', '\xED', '\xEE', '\xEF',
+    '\xF0', '\xF1', '\xF2', '\xF3', '\xF4', '\xF5', '\xF6', '\xF7',
+    '\xF8', '\xF9', '\xFA', '\xFB', '\xFC', '\xFD', '\xFE', '\xFF'
+];
+
+static ISO_8859_2_HIGH: [char, ..128] = [
+    '\x80', '\x81', '\x82', '\x83', '\x84', '\x85', '\x86', '\x87',
+    '\x88', '\x89', '\x8A', '\x8B', '\x8C', '\x8D', '\x8E', '\x8F',
+    '\x90', '\x91', '\x92', '\x93', '\x94', '\x95', '\x96', '\x97',
+    '\x98', '\x99', '\x9A', '\x9B', '\x9C', '\x9D', '\x9E', '\x9F',
+    ' ', 'Ą', '˘', 'Ł', '¤', 'Ľ', 'Ś', '§',
+    '¨', 'Š', 'Ş', 'Ť', 'Ź', '­', 'Ž', 'Ż',
+    '°', 'ą', '˛', 'ł', '´', 'ľ', 'ś', 'ˇ',
+    '¸', 'š', 'ş', 'ť', 'ź', '˝', 'ž', 'ż',
+    'Ŕ', 'Á', 'Â', 'Ă', 'Ä', 'Ĺ', 'Ć', 'Ç',
+    'Č', 'É', 'Ę', 'Ë', 'Ě', 'Í', 'Î', 'Ď',
+    'Đ', 'Ń', 'Ň', 'Ó', 'Ô', 'Ő', 'Ö', '×',
+    'Ř', 'Ů', 'Ú', 'Ű', 'Ü', 'Ý', 'Ţ', 'ß',
+    'ŕ', 'á', 'â', 'ă', 'ä', 'ĺ', 'ć', 'ç',
+    'č', 'é', 'ę', 'ë', 'ě', 'í', 'î', 'ď',
+    'đ', 'ń', 'ň', 'ó', 'ô', 'ő', 'ö', '÷',
+    'ř', 'ů', 'ú', 'ű', 'ü', 'ý', 'ţ', '˙'
+];
+
+static ISO_8859_15_HIGH: [char, ..128] = [
+    '\x80', '\x81', '\x82', '\x83', '\x84', '\x85', '\x86', '\x87',
+    '\x88', '\x89', '\x8A', '\x8B', '\x8C', '\x8D', '\x8E', '\x8F',
+    '\x90', '\x91', '\x92', '\x93', '\x94', '\x95', '\x96', '\x97',
+    '\x98', '\x99', '\x9A', '\x9B', '\x9C', '\x9D', '\x9E', '\x9F',
+    '\xA0', '\xA1', '\xA2', '\xA3', '€', '\xA5', 'Š', '\xA7',
+    'š', '\xA9', '\xAA', '\xAB', '\xAC', '\xAD', '\xAE', '\xAF',
+    '\xB0', '\xB1', '\xB2', '\xB3', 'Ž', '\xB5', '\xB6', '\xB7',
+    'ž', '\xB9', '\xBA', '\xBB', 'Œ', 'œ', 'Ÿ', '\xBF',
+    '\xC0', '\xC1', '\xC2', '\xC3', '\xC4', '\xC5', '\xC6', '\xC7',
+    '\xC8', '\xC9', '\xCA', '\xCB', '\xCC', '\xCD', '\xCE', '\xCF',
+    '\xD0', '\xD1', '\xD2', '\xD3', '\xD4', '\xD5', '\xD6', '\xD7',
+    '\xD8', '\xD9', '\xDA', '\xDB', '\xDC', '\xDD', '\xDE', '\xDF',
+    '\xE0', '\xE1', '\xE2', '\xE3', '\xE4', '\xE5', '\xE6', '\xE7',
+    '\xE8', '\xE9', '\xEA', '\xEB', '\xEC', '\xED', '\xEE', '\xEF',
+    '\xF0', '\xF1', '\xF2', '\xF3', '\xF4', '\xF5', '\xF6', '\xF7',
+    '\xF8', '\xF9', '\xFA', '\xFB', '\xFC', '\xFD', '\xFE', '\xFF'
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use iterator::IteratorUtil;
+
+    #[test]
+    fn test_windows_1252_decode() {
+        let a = ['h' as u8, 0x80, 'i' as u8];
+        assert_eq!(a.decode_as(windows_1252).collect::<~[char]>(),
+                   ~['h', '€', 'i']);
+    }
+
+    #[test]
+    fn test_windows_1252_encode() {
+        let a = ['h', '€', 'i'];
+        assert_eq!(a.encode_as(windows_1252).collect::<~[u8]>(),
+                   ~['h' as u8, 0x80, 'i' as u8]);
+    }
+
+    #[test]
+    fn test_windows_1252_decode_c1_controls() {
+        // the bytes Microsoft's CP1252 leaves unmapped decode to the C1
+        // control code points the WHATWG windows-1252 index assigns them
+        let a = [0x81u8, 0x8Du8, 0x8Fu8, 0x90u8, 0x9Du8];
+        assert_eq!(a.decode_as(windows_1252).collect::<~[char]>(),
+                   ~['\x81', '\x8D', '\x8F', '\x90', '\x9D']);
+    }
+
+    #[test]
+    fn test_windows_1252_encode_sentinel_char_is_out_of_range() {
+        // the UNMAPPED sentinel itself is never matched against a table
+        // slot, so encoding it is out of range and falls back to '?'
+        let a = [UNMAPPED];
+        assert_eq!(a.encode_as(windows_1252).collect::<~[u8]>(), ~['?' as u8]);
+    }
+
+    #[test]
+    fn test_iso_8859_2_roundtrip() {
+        let a = ['Ł', 'ó', 'd', 'ź'];
+        let bytes = a.encode_as(iso_8859_2).collect::<~[u8]>();
+        assert_eq!(bytes.decode_as(iso_8859_2).collect::<~[char]>(), a.to_owned());
+    }
+
+    #[test]
+    fn test_iso_8859_15_euro() {
+        let a = ['€'];
+        assert_eq!(a.encode_as(iso_8859_15).collect::<~[u8]>(), ~[0xA4u8]);
+    }
+}
@@ -8,14 +8,17 @@
 // option. This file may not be copied, modified, or distributed
 // except according to those terms.
 
-use encoding::{Encoder, Decoder};
+use encoding::{Encoder, Decoder, TryEncoder, TryDecoder, StreamEncoder, StreamDecoder};
 use encoding::{DecodeAsReplacementChar, DecodeAs, SkipInvalidByte,
                TruncateDecoding, FailDecoding};
 use encoding::{EncodeAsReplacementChar, EncodeAs, SkipOutOfRangeChar,
                TruncateEncoding, FailEncoding};
+use encoding::{DecodeError, UnexpectedEnd, InvalidSequence, EncodeError};
 use char::ReplacementChar;
+use io::{Reader, ReaderUtil, Writer};
 use iterator::Iterator;
 use option::{Option, None, Some};
+use result::{Result, Ok, Err};
 use to_bytes::IterBytes;
 use uint;
 use vec::{CopyableVector, ImmutableVector, MutableVector, MutableCloneableVector};
@@ -54,6 +57,10 @@ impl<T: Iterator<u8>> Decoder<T, UTF16Decoder<T>> for utf16 {
     }
 }
 
+fn is_valid_scalar(c: u32) -> bool {
+    (c < 0xD800 || c > 0xDBFF) && (c < 0xDC00 || c > 0xDFFF) && c <= 0x10FFFF
+}
+
 pub struct UTF16Encoder<T> {
     priv iter: T,
     priv bom: bool,
@@ -63,50 +70,74 @@ pub struct UTF16Encoder<T> {
     priv hi: uint
 }
 
-impl<T: Iterator<char>> Iterator<u8> for UTF16Encoder<T> {
+impl<T: Iterator<char>> UTF16Encoder<T> {
+    /// Emits a leading BOM, if this encoder was constructed with one pending.
+    /// Shared by the condition-based and fallible encoding paths.
     #[inline]
-    fn next(&mut self) -> Option<u8> {
-        use encoding::out_of_range::cond;
-
+    fn emit_bom(&mut self) {
         if self.bom {
             self.lo = 0;
             for 0xFEFFu16.iter_bytes(!self.big) |b| { self.hi = self.buf.copy_from(b); }
             self.bom = false;
         }
+    }
+
+    /// Pulls the next char off the source iterator and validates it as a
+    /// Unicode scalar value, without yet encoding it. Shared by both paths.
+    #[inline]
+    fn step(&mut self) -> Option<Result<u32, EncodeError>> {
+        let c = match self.iter.next() { Some(c) => c, None => return None };
+        let cv = c as u32;
+        if is_valid_scalar(cv) { Some(Ok(cv)) } else { Some(Err(EncodeError{ c: c })) }
+    }
+
+    /// Encodes an already-validated scalar value into `buf`. Shared by both paths.
+    #[inline]
+    fn fill_buf(&mut self, mut c: u32) {
+        self.lo = 0;
+        if c > 0xFFFF {
+            c -= 0x10000;
+            let lead = (0xD800 + (c >> 10)) as u16;
+            let trail = (0xDC00 + (c & 0x3FF)) as u16;
+            self.hi = 0;
+            for [lead, trail].iter_bytes(!self.big) |b| {
+                self.hi += self.buf.mut_slice(self.hi, 4).copy_from(b);
+            }
+        } else {
+            self.hi = 0;
+            for (c as u16).iter_bytes(!self.big) |b| { self.hi += self.buf.copy_from(b); }
+        }
+    }
+}
+
+impl<T: Iterator<char>> Iterator<u8> for UTF16Encoder<T> {
+    #[inline]
+    fn next(&mut self) -> Option<u8> {
+        use encoding::out_of_range::cond;
+
+        self.emit_bom();
         if self.lo == self.hi {
             loop {
-                let c = self.iter.next();
-                if c.is_none() { return None }
-                let mut c = c.unwrap() as u32;
-                fn is_valid(c: u32) -> bool {
-                    (c < 0xD800 || c > 0xDBFF) && (c < 0xDC00 || c > 0xDFFF) && c <= 0x10FFFF
-                }
-                if !is_valid(c) {
-                    match cond.raise_default(c as char, || EncodeAsReplacementChar ) {
-                        EncodeAsReplacementChar => c = ReplacementChar as u32,
-                        EncodeAs(c_) => c = c_ as u32,
-                        SkipOutOfRangeChar => loop,
-                        TruncateEncoding => return None,
-                        FailEncoding => fail!("out-of-range char 0x%x found", c as uint)
-                    }
-                    if !is_valid(c) {
-                        fail!("out-of-range har 0x%x found", c as uint);
-                    }
-                }
-                self.lo = 0;
-                if c > 0xFFFF {
-                    c -= 0x10000;
-                    let lead = (0xD800 + (c >> 10)) as u16;
-                    let trail = (0xDC00 + (c & 0x3FF)) as u16;
-                    self.hi = 0;
-                    for [lead, trail].iter_bytes(!self.big) |b| {
-                        self.hi += self.buf.mut_slice(self.hi, 4).copy_from(b);
+                match self.step() {
+                    None => return None,
+                    Some(Ok(c)) => { self.fill_buf(c); break; }
+                    Some(Err(EncodeError{ c })) => {
+                        match cond.raise_default(c, || EncodeAsReplacementChar ) {
+                            EncodeAsReplacementChar => { self.fill_buf(ReplacementChar as u32); }
+                            EncodeAs(c_) => {
+                                let cv = c_ as u32;
+                                if !is_valid_scalar(cv) {
+                                    fail!("out-of-range char 0x%x found", cv as uint);
+                                }
+                                self.fill_buf(cv);
+                            }
+                            SkipOutOfRangeChar => loop,
+                            TruncateEncoding => return None,
+                            FailEncoding => fail!("out-of-range char 0x%x found", c as uint)
+                        }
+                        break;
                     }
-                } else {
-                    self.hi = 0;
-                    for (c as u16).iter_bytes(!self.big) |b| { self.hi += self.buf.copy_from(b); }
                 }
-                break;
             }
         }
         let r = Some(self.buf[self.lo]);
@@ -130,6 +161,97 @@ impl<T: Iterator<char>> Iterator<u8> for UTF16Encoder<T> {
     }
 }
 
+/// A fallible counterpart to `UTF16Encoder`, yielding `Result<u8, EncodeError>`
+/// instead of raising the out_of_range condition on an unrepresentable char.
+pub struct UTF16TryEncoder<T> {
+    priv inner: UTF16Encoder<T>
+}
+
+impl<T: Iterator<char>> Iterator<Result<u8, EncodeError>> for UTF16TryEncoder<T> {
+    #[inline]
+    fn next(&mut self) -> Option<Result<u8, EncodeError>> {
+        let enc = &mut self.inner;
+        enc.emit_bom();
+        if enc.lo == enc.hi {
+            match enc.step() {
+                None => return None,
+                Some(Ok(c)) => enc.fill_buf(c),
+                Some(Err(e)) => return Some(Err(e))
+            }
+        }
+        let r = enc.buf[enc.lo];
+        enc.lo += 1;
+        Some(Ok(r))
+    }
+
+    #[inline]
+    fn size_hint(&self) -> (uint, Option<uint>) {
+        self.inner.size_hint()
+    }
+}
+
+impl<T: Iterator<char>> TryEncoder<T, UTF16TryEncoder<T>> for utf16 {
+    fn try_encode(&self, src: T) -> UTF16TryEncoder<T> {
+        UTF16TryEncoder{ inner: self.encode(src) }
+    }
+}
+
+/// A streaming counterpart to `UTF16Encoder`: encodes one char at a time
+/// straight into a `Writer`, rather than collecting into an in-memory byte
+/// iterator, so a caller can feed it one char per `encode_step` call.
+pub struct UTF16StreamEncoder {
+    priv bom: bool,
+    priv big: bool
+}
+
+impl utf16 {
+    /// Creates a streaming encoder that writes directly to the `Writer`
+    /// given to `encode_step`, rather than producing an in-memory iterator.
+    pub fn stream_encoder(&self) -> UTF16StreamEncoder {
+        match *self {
+            utf16 => UTF16StreamEncoder{ bom: true, big: true },
+            utf16be => UTF16StreamEncoder{ bom: false, big: true },
+            utf16le => UTF16StreamEncoder{ bom: false, big: false }
+        }
+    }
+}
+
+impl StreamEncoder for UTF16StreamEncoder {
+    fn encode_step(&mut self, c: char, dst: &mut Writer) {
+        use encoding::out_of_range::cond;
+
+        if self.bom {
+            for 0xFEFFu16.iter_bytes(!self.big) |b| { dst.write(b); }
+            self.bom = false;
+        }
+
+        let mut cv = c as u32;
+        if !is_valid_scalar(cv) {
+            match cond.raise_default(c, || EncodeAsReplacementChar) {
+                EncodeAsReplacementChar => cv = ReplacementChar as u32,
+                EncodeAs(c_) => {
+                    cv = c_ as u32;
+                    if !is_valid_scalar(cv) {
+                        fail!("out-of-range char 0x%x found", cv as uint);
+                    }
+                }
+                // there's no stream to truncate, so just drop this char
+                SkipOutOfRangeChar | TruncateEncoding => return,
+                FailEncoding => fail!("out-of-range char 0x%x found", c as uint)
+            }
+        }
+
+        if cv > 0xFFFF {
+            cv -= 0x10000;
+            let lead = (0xD800 + (cv >> 10)) as u16;
+            let trail = (0xDC00 + (cv & 0x3FF)) as u16;
+            for [lead, trail].iter_bytes(!self.big) |b| { dst.write(b); }
+        } else {
+            for (cv as u16).iter_bytes(!self.big) |b| { dst.write(b); }
+        }
+    }
+}
+
 pub struct UTF16Decoder<T> {
     priv iter: Option<T>,
     priv bom: bool,
@@ -137,13 +259,13 @@ pub struct UTF16Decoder<T> {
     priv c: Option<char>
 }
 
-impl<T: Iterator<u8>> Iterator<char> for UTF16Decoder<T> {
-    #[inline]
-    fn next(&mut self) -> Option<char> {
-        use encoding::invalid_byte::cond;
-
+impl<T: Iterator<u8>> UTF16Decoder<T> {
+    /// Decodes the next char, returning an `Err` describing what went wrong
+    /// in place of raising the invalid_byte condition. Shared by the
+    /// condition-based and fallible decoding paths.
+    fn step(&mut self) -> Option<Result<char, DecodeError>> {
         if self.c.is_some() {
-            return Some(self.c.swap_unwrap());
+            return Some(Ok(self.c.swap_unwrap()));
         }
 
         if self.iter.is_none() {
@@ -160,13 +282,7 @@ impl<T: Iterator<u8>> Iterator<char> for UTF16Decoder<T> {
             if b.is_none() {
                 // half a codepoint?
                 self.iter = None;
-                match cond.raise_default(None, || DecodeAsReplacementChar) {
-                    DecodeAsReplacementChar => return Some(ReplacementChar),
-                    DecodeAs(c) => return Some(c),
-                    SkipInvalidByte => return None, // stream is empty
-                    TruncateDecoding => return None,
-                    FailDecoding => fail!("bytestream terminated unexpectedly")
-                }
+                return Some(Err(DecodeError{ kind: UnexpectedEnd, bytes: None }));
             }
             let b = b.unwrap();
 
@@ -200,7 +316,7 @@ impl<T: Iterator<u8>> Iterator<char> for UTF16Decoder<T> {
                     let (lead, _, _) = lead.unwrap();
                     let lead = (lead as u32 - 0xD800) << 10;
                     let trail = c as u32 - 0xDC00;
-                    return Some(((lead | trail) + 0x10000) as char);
+                    return Some(Ok(((lead | trail) + 0x10000) as char));
                 }
                 valid = false;
             } else if lead.is_some() {
@@ -212,16 +328,42 @@ impl<T: Iterator<u8>> Iterator<char> for UTF16Decoder<T> {
                 self.c = Some(c as char);
             }
             if !valid {
-                match cond.raise_default(Some(arg.to_owned()), || DecodeAsReplacementChar) {
-                    DecodeAsReplacementChar => return Some(ReplacementChar),
-                    DecodeAs(c) => return Some(c),
-                    SkipInvalidByte => loop,
-                    TruncateDecoding => return None,
-                    FailDecoding => fail!("invalid byte sequence encountered")
-                }
+                return Some(Err(DecodeError{ kind: InvalidSequence, bytes: Some(arg.to_owned()) }));
             }
 
-            return Some(c as char);
+            return Some(Ok(c as char));
+        }
+    }
+}
+
+impl<T: Iterator<u8>> Iterator<char> for UTF16Decoder<T> {
+    #[inline]
+    fn next(&mut self) -> Option<char> {
+        use encoding::invalid_byte::cond;
+
+        loop {
+            match self.step() {
+                None => return None,
+                Some(Ok(c)) => return Some(c),
+                Some(Err(DecodeError{ kind: UnexpectedEnd, bytes: _ })) => {
+                    return match cond.raise_default(None, || DecodeAsReplacementChar) {
+                        DecodeAsReplacementChar => Some(ReplacementChar),
+                        DecodeAs(c) => Some(c),
+                        SkipInvalidByte => None, // stream is empty
+                        TruncateDecoding => None,
+                        FailDecoding => fail!("bytestream terminated unexpectedly")
+                    }
+                }
+                Some(Err(DecodeError{ kind: InvalidSequence, bytes })) => {
+                    match cond.raise_default(bytes, || DecodeAsReplacementChar) {
+                        DecodeAsReplacementChar => return Some(ReplacementChar),
+                        DecodeAs(c) => return Some(c),
+                        SkipInvalidByte => loop,
+                        TruncateDecoding => return None,
+                        FailDecoding => fail!("invalid byte sequence encountered")
+                    }
+                }
+            }
         }
     }
 
@@ -238,10 +380,160 @@ impl<T: Iterator<u8>> Iterator<char> for UTF16Decoder<T> {
     }
 }
 
+/// A fallible counterpart to `UTF16Decoder`, yielding `Result<char, DecodeError>`
+/// instead of raising the invalid_byte condition.
+pub struct UTF16TryDecoder<T> {
+    priv inner: UTF16Decoder<T>
+}
+
+impl<T: Iterator<u8>> Iterator<Result<char, DecodeError>> for UTF16TryDecoder<T> {
+    #[inline]
+    fn next(&mut self) -> Option<Result<char, DecodeError>> {
+        self.inner.step()
+    }
+
+    #[inline]
+    fn size_hint(&self) -> (uint, Option<uint>) {
+        self.inner.size_hint()
+    }
+}
+
+impl<T: Iterator<u8>> TryDecoder<T, UTF16TryDecoder<T>> for utf16 {
+    fn try_decode(&self, src: T) -> UTF16TryDecoder<T> {
+        UTF16TryDecoder{ inner: self.decode(src) }
+    }
+}
+
+/// A streaming counterpart to `UTF16Decoder`: decodes directly from a
+/// `Reader` passed to `decode_step`, rather than owning an `Iterator<u8>`.
+/// Holds the same state across calls that `UTF16Decoder` holds across
+/// buffer boundaries -- a pending byte order and a char decoded but not yet
+/// returned -- so a codepoint split across two `read`s still decodes
+/// correctly.
+pub struct UTF16StreamDecoder {
+    priv bom: bool,
+    priv big: bool,
+    priv c: Option<char>
+}
+
+impl utf16 {
+    /// Creates a streaming decoder that pulls its bytes from the `Reader`
+    /// given to `decode_step`, rather than from an in-memory iterator.
+    pub fn stream_decoder(&self) -> UTF16StreamDecoder {
+        match *self {
+            utf16 => UTF16StreamDecoder{ bom: true, big: true, c: None },
+            utf16be => UTF16StreamDecoder{ bom: false, big: true, c: None },
+            utf16le => UTF16StreamDecoder{ bom: false, big: false, c: None }
+        }
+    }
+}
+
+impl UTF16StreamDecoder {
+    /// Same algorithm as `UTF16Decoder::step`, driven by a `Reader` instead
+    /// of an owned iterator. The dangling-lead-surrogate state is scoped to
+    /// a single call (as in `UTF16Decoder::step`) since a call doesn't
+    /// return until it has a full codepoint, an error, or EOF.
+    fn step(&mut self, src: &mut Reader) -> Option<Result<char, DecodeError>> {
+        if self.c.is_some() {
+            return Some(Ok(self.c.swap_unwrap()));
+        }
+
+        let mut lead = None;
+
+        loop {
+            let a = match src.read_byte() { Some(a) => a, None => return None };
+            let b = match src.read_byte() {
+                Some(b) => b,
+                None => return Some(Err(DecodeError{ kind: UnexpectedEnd, bytes: None }))
+            };
+
+            if self.bom {
+                self.bom = false;
+                if a == 0xFE && b == 0xFF {
+                    self.big = true;
+                    loop;
+                } else if a == 0xFF && b == 0xFE {
+                    self.big = false;
+                    loop;
+                }
+            }
+
+            let c = if self.big {
+                (a as u16 << 8) | (b as u16)
+            } else {
+                (b as u16 << 8) | (a as u16)
+            };
+
+            let mut valid = true;
+            let mut arg = [a, b];
+            if c >= 0xD800 && c <= 0xDBFF {
+                if lead.is_none() {
+                    lead = Some((c, a, b));
+                    loop;
+                }
+                valid = false;
+            } else if c >= 0xDC00 && c <= 0xDFFF {
+                if lead.is_some() {
+                    let (lead, _, _) = lead.unwrap();
+                    let lead = (lead as u32 - 0xD800) << 10;
+                    let trail = c as u32 - 0xDC00;
+                    return Some(Ok(((lead | trail) + 0x10000) as char));
+                }
+                valid = false;
+            } else if lead.is_some() {
+                valid = false;
+                let (_, a_, b_) = lead.unwrap();
+                arg[0] = a_;
+                arg[1] = b_;
+                self.c = Some(c as char);
+            }
+            if !valid {
+                return Some(Err(DecodeError{ kind: InvalidSequence, bytes: Some(arg.to_owned()) }));
+            }
+
+            return Some(Ok(c as char));
+        }
+    }
+}
+
+impl StreamDecoder for UTF16StreamDecoder {
+    fn decode_step(&mut self, src: &mut Reader) -> Option<char> {
+        use encoding::invalid_byte::cond;
+
+        loop {
+            match self.step(src) {
+                None => return None,
+                Some(Ok(c)) => return Some(c),
+                Some(Err(DecodeError{ kind: UnexpectedEnd, bytes: _ })) => {
+                    return match cond.raise_default(None, || DecodeAsReplacementChar) {
+                        DecodeAsReplacementChar => Some(ReplacementChar),
+                        DecodeAs(c) => Some(c),
+                        SkipInvalidByte => None, // stream is empty
+                        TruncateDecoding => None,
+                        FailDecoding => fail!("bytestream terminated unexpectedly")
+                    }
+                }
+                Some(Err(DecodeError{ kind: InvalidSequence, bytes })) => {
+                    match cond.raise_default(bytes, || DecodeAsReplacementChar) {
+                        DecodeAsReplacementChar => return Some(ReplacementChar),
+                        DecodeAs(c) => return Some(c),
+                        SkipInvalidByte => loop,
+                        TruncateDecoding => return None,
+                        FailDecoding => fail!("invalid byte sequence encountered")
+                    }
+                }
+            }
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use encoding::{DecodeError, InvalidSequence, EncodeError};
+    use io::{Reader, Writer};
     use iterator::IteratorUtil;
+    use result::{Ok, Err};
 
     #[test]
     fn test_utf16be_encode() {
@@ -326,4 +618,80 @@ mod tests {
         assert_eq!(a.reencode(utf16le,utf16be).collect::<~[u8]>(),
                    ~[0xD8u8, 0x47u8, 0xDEu8, 0x33u8, 0xD8u8, 0x7Eu8, 0xDCu8, 0xB6u8]);
     }
+
+    #[test]
+    fn test_utf16be_try_decode_ok() {
+        let a = [0, 't' as u8, 0, 'e' as u8, 0, 's' as u8, 0, 't' as u8];
+        let mut it = a.try_decode_as(utf16be);
+        for &expected in ['t', 'e', 's', 't'].iter() {
+            match it.next() {
+                Some(Ok(c)) => assert_eq!(c, expected),
+                _ => fail!("expected a decoded char")
+            }
+        }
+        match it.next() {
+            None => (),
+            _ => fail!("expected end of stream")
+        }
+    }
+
+    #[test]
+    fn test_utf16be_try_decode_invalid_sequence() {
+        // an unpaired low surrogate has no condition handler installed
+        let a = [0xDCu8, 0x00u8, 0, 't' as u8];
+        let mut it = a.try_decode_as(utf16be);
+        match it.next() {
+            Some(Err(DecodeError{ kind: InvalidSequence, bytes: _ })) => (),
+            _ => fail!("expected InvalidSequence")
+        }
+        match it.next() {
+            Some(Ok(c)) => assert_eq!(c, 't'),
+            _ => fail!("expected a decoded char")
+        }
+        match it.next() {
+            None => (),
+            _ => fail!("expected end of stream")
+        }
+    }
+
+    #[test]
+    fn test_utf16be_try_encode() {
+        let a = ['t', 'e', 's', 't'];
+        let mut it = a.try_encode_as(utf16be);
+        for &expected in [0u8, 't' as u8, 0u8, 'e' as u8, 0u8, 's' as u8, 0u8, 't' as u8].iter() {
+            match it.next() {
+                Some(Ok(b)) => assert_eq!(b, expected),
+                _ => fail!("expected an encoded byte")
+            }
+        }
+        match it.next() {
+            None => (),
+            _ => fail!("expected end of stream")
+        }
+    }
+
+    #[test]
+    fn test_utf16be_stream_decode() {
+        use io::mem::MemReader;
+
+        // a surrogate pair followed by a BMP char, read a byte at a time from
+        // the `Reader`; each `decode_step` call returns one whole char
+        let mut r = MemReader::new(~[0xD8u8, 0x47u8, 0xDEu8, 0x33u8, 0, 't' as u8]);
+        let mut dec = utf16be.stream_decoder();
+
+        assert_eq!(dec.decode_step(&mut r as &mut Reader), Some('𡸳'));
+        assert_eq!(dec.decode_step(&mut r as &mut Reader), Some('t'));
+        assert_eq!(dec.decode_step(&mut r as &mut Reader), None);
+    }
+
+    #[test]
+    fn test_utf16be_stream_encode() {
+        use io::mem::MemWriter;
+
+        let mut w = MemWriter::new();
+        let mut enc = utf16be.stream_encoder();
+        for c in ['t', 'e', 's', 't'].iter() { enc.encode_step(*c, &mut w as &mut Writer); }
+        assert_eq!(w.inner_ref().as_slice(),
+                   [0u8, 't' as u8, 0u8, 'e' as u8, 0u8, 's' as u8, 0u8, 't' as u8]);
+    }
 }
@@ -0,0 +1,264 @@
+// Copyright 2013 The Rust Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution and at
+// http://rust-lang.org/COPYRIGHT.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use encoding::{Encoder, Decoder};
+use encoding::{DecodeAsReplacementChar, DecodeAs, SkipInvalidByte,
+               TruncateDecoding, FailDecoding};
+use encoding::{EncodeAsReplacementChar, EncodeAs, SkipOutOfRangeChar,
+               TruncateEncoding, FailEncoding};
+use char::ReplacementChar;
+use iterator::Iterator;
+use option::{Option, None, Some};
+use to_bytes::IterBytes;
+use uint;
+use vec::{CopyableVector, ImmutableVector, MutableVector, MutableCloneableVector};
+
+#[allow(non_camel_case_types)]
+pub enum utf32 {
+    /// UTF-32, emits a BOM on encoding and consumes one on decoding.
+    /// UTF-32BE is used for encoding, and assumed if there is no BOM on decoding.
+    utf32,
+    /// UTF-32BE
+    utf32be,
+    /// UTF-32LE
+    utf32le,
+}
+
+impl<T: Iterator<char>> Encoder<T, UTF32Encoder<T>> for utf32 {
+    fn encode(&self, src: T) -> UTF32Encoder<T> {
+        match *self {
+            utf32 => UTF32Encoder{ iter: src, bom: true, big: true, buf: [0, ..4], lo: 0, hi: 0 },
+            utf32be => UTF32Encoder{ iter: src, bom: false, big: true, buf: [0, ..4], lo: 0, hi: 0 },
+            utf32le => UTF32Encoder{ iter: src, bom: false, big: false, buf: [0, ..4], lo: 0, hi: 0 }
+        }
+    }
+}
+
+impl<T: Iterator<u8>> Decoder<T, UTF32Decoder<T>> for utf32 {
+    fn decode(&self, src: T) -> UTF32Decoder<T> {
+        match *self {
+            utf32 => UTF32Decoder{ iter: Some(src), bom: true, big: true },
+            utf32be => UTF32Decoder{ iter: Some(src), bom: false, big: true },
+            utf32le => UTF32Decoder{ iter: Some(src), bom: false, big: false }
+        }
+    }
+}
+
+// code points above this, and the surrogate range, are not valid scalar values
+fn is_valid_scalar(c: u32) -> bool {
+    (c < 0xD800 || c > 0xDFFF) && c <= 0x10FFFF
+}
+
+pub struct UTF32Encoder<T> {
+    priv iter: T,
+    priv bom: bool,
+    priv big: bool,
+    priv buf: [u8, ..4],
+    priv lo: uint,
+    priv hi: uint
+}
+
+impl<T: Iterator<char>> Iterator<u8> for UTF32Encoder<T> {
+    #[inline]
+    fn next(&mut self) -> Option<u8> {
+        use encoding::out_of_range::cond;
+
+        if self.bom {
+            self.lo = 0;
+            for 0xFEFFu32.iter_bytes(!self.big) |b| { self.hi = self.buf.copy_from(b); }
+            self.bom = false;
+        }
+        if self.lo == self.hi {
+            loop {
+                let c = self.iter.next();
+                if c.is_none() { return None }
+                let mut c = c.unwrap() as u32;
+                if !is_valid_scalar(c) {
+                    match cond.raise_default(c as char, || EncodeAsReplacementChar ) {
+                        EncodeAsReplacementChar => c = ReplacementChar as u32,
+                        EncodeAs(c_) => c = c_ as u32,
+                        SkipOutOfRangeChar => loop,
+                        TruncateEncoding => return None,
+                        FailEncoding => fail!("out-of-range char 0x%x found", c as uint)
+                    }
+                    if !is_valid_scalar(c) {
+                        fail!("out-of-range char 0x%x found", c as uint);
+                    }
+                }
+                self.lo = 0;
+                self.hi = 0;
+                for c.iter_bytes(!self.big) |b| {
+                    self.hi += self.buf.mut_slice(self.hi, 4).copy_from(b);
+                }
+                break;
+            }
+        }
+        let r = Some(self.buf[self.lo]);
+        self.lo += 1;
+        r
+    }
+
+    #[inline]
+    fn size_hint(&self) -> (uint, Option<uint>) {
+        // always exactly 4 bytes per char
+        let (lo, hi) = self.iter.size_hint();
+        let lo = if lo > uint::max_value / 4 { uint::max_value } else { lo*4 };
+        let hi = do hi.chain |x| {
+            if x > uint::max_value / 4 { None } else { Some(x*4) }
+        };
+        (lo, hi)
+    }
+}
+
+pub struct UTF32Decoder<T> {
+    priv iter: Option<T>,
+    priv bom: bool,
+    priv big: bool
+}
+
+impl<T: Iterator<u8>> Iterator<char> for UTF32Decoder<T> {
+    #[inline]
+    fn next(&mut self) -> Option<char> {
+        use encoding::invalid_byte::cond;
+
+        if self.iter.is_none() { return None; }
+
+        loop {
+            let mut buf = [0u8, ..4];
+            let mut n = 0;
+            while n < 4 {
+                let b = self.iter.get_mut_ref().next();
+                if b.is_none() { break }
+                buf[n] = b.unwrap();
+                n += 1;
+            }
+            if n == 0 { self.iter = None; return None; }
+            if n < 4 {
+                self.iter = None;
+                match cond.raise_default(Some(buf.slice(0, n).to_owned()), || DecodeAsReplacementChar) {
+                    DecodeAsReplacementChar => return Some(ReplacementChar),
+                    DecodeAs(c) => return Some(c),
+                    SkipInvalidByte => return None,
+                    TruncateDecoding => return None,
+                    FailDecoding => fail!("bytestream terminated unexpectedly")
+                }
+            }
+
+            let c = if self.big {
+                (buf[0] as u32 << 24) | (buf[1] as u32 << 16) | (buf[2] as u32 << 8) | (buf[3] as u32)
+            } else {
+                (buf[3] as u32 << 24) | (buf[2] as u32 << 16) | (buf[1] as u32 << 8) | (buf[0] as u32)
+            };
+
+            if self.bom {
+                self.bom = false;
+                if c == 0xFEFF {
+                    loop;
+                } else if buf == [0xFF, 0xFE, 0, 0] {
+                    self.big = false;
+                    loop;
+                }
+            }
+
+            if !is_valid_scalar(c) {
+                match cond.raise_default(Some(buf.to_owned()), || DecodeAsReplacementChar) {
+                    DecodeAsReplacementChar => return Some(ReplacementChar),
+                    DecodeAs(c) => return Some(c),
+                    SkipInvalidByte => loop,
+                    TruncateDecoding => return None,
+                    FailDecoding => fail!("invalid byte sequence encountered")
+                }
+            }
+
+            return Some(c as char);
+        }
+    }
+
+    #[inline]
+    fn size_hint(&self) -> (uint, Option<uint>) {
+        // 4 bytes per char; round hi up, a trailing partial char could turn
+        // into a char based on condition handling
+        let (lo, hi) = self.iter.map_default((0, None), |it| it.size_hint());
+        let lo = if lo == uint::max_value { uint::max_value } else { lo / 4 };
+        let hi = do hi.map_consume |x| { x / 4 + if x % 4 == 0 { 0 } else { 1 } };
+        (lo, hi)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use char::ReplacementChar;
+    use iterator::IteratorUtil;
+
+    #[test]
+    fn test_utf32be_encode() {
+        let a = ['t', 'e', 's', 't'];
+        assert_eq!(a.encode_as(utf32be).collect::<~[u8]>(),
+                   ~[0, 0, 0, 't' as u8, 0, 0, 0, 'e' as u8, 0, 0, 0, 's' as u8, 0, 0, 0, 't' as u8]);
+
+        let b = ['𡸳'];
+        assert_eq!(b.encode_as(utf32be).collect::<~[u8]>(),
+                   ~[0x00, 0x02, 0x1E, 0x33]);
+    }
+
+    #[test]
+    fn test_utf32le_encode() {
+        let a = ['t', 'e', 's', 't'];
+        assert_eq!(a.encode_as(utf32le).collect::<~[u8]>(),
+                   ~['t' as u8, 0, 0, 0, 'e' as u8, 0, 0, 0, 's' as u8, 0, 0, 0, 't' as u8, 0, 0, 0]);
+    }
+
+    #[test]
+    fn test_utf32_encode_bom() {
+        let a = ['t'];
+        assert_eq!(a.encode_as(utf32).collect::<~[u8]>(),
+                   ~[0, 0, 0xFE, 0xFF, 0, 0, 0, 't' as u8]);
+    }
+
+    #[test]
+    fn test_utf32be_decode() {
+        let a = [0, 0, 0, 't' as u8, 0, 0, 0, 'e' as u8, 0, 0, 0, 's' as u8, 0, 0, 0, 't' as u8];
+        assert_eq!(a.decode_as(utf32be).collect::<~[char]>(),
+                   ~['t', 'e', 's', 't']);
+    }
+
+    #[test]
+    fn test_utf32le_decode() {
+        let a = ['t' as u8, 0, 0, 0, 'e' as u8, 0, 0, 0, 's' as u8, 0, 0, 0, 't' as u8, 0, 0, 0];
+        assert_eq!(a.decode_as(utf32le).collect::<~[char]>(),
+                   ~['t', 'e', 's', 't']);
+    }
+
+    #[test]
+    fn test_utf32_decode_bom() {
+        let a = [0, 0, 0xFE, 0xFF, 0, 0, 0, 't' as u8];
+        assert_eq!(a.decode_as(utf32).collect::<~[char]>(),
+                   ~['t']);
+
+        let b = [0xFF, 0xFE, 0, 0, 't' as u8, 0, 0, 0];
+        assert_eq!(b.decode_as(utf32).collect::<~[char]>(),
+                   ~['t']);
+    }
+
+    #[test]
+    fn test_utf32_decode_surrogate_rejected() {
+        let a = [0, 0, 0xD8u8, 0x00u8];
+        assert_eq!(a.decode_as(utf32be).collect::<~[char]>(),
+                   ~[ReplacementChar]);
+    }
+
+    #[test]
+    fn test_utf32_decode_out_of_range_rejected() {
+        let a = [0x01u8, 0, 0, 0];
+        assert_eq!(a.decode_as(utf32be).collect::<~[char]>(),
+                   ~[ReplacementChar]);
+    }
+}
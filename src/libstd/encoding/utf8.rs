@@ -0,0 +1,312 @@
+// Copyright 2013 The Rust Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution and at
+// http://rust-lang.org/COPYRIGHT.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use encoding::{Encoder, Decoder};
+use encoding::{DecodeAsReplacementChar, DecodeAs, SkipInvalidByte,
+               TruncateDecoding, FailDecoding};
+use encoding::{EncodeAsReplacementChar, EncodeAs, SkipOutOfRangeChar,
+               TruncateEncoding, FailEncoding};
+use char::ReplacementChar;
+use iterator::Iterator;
+use option::{Option, None, Some};
+use uint;
+use vec::{ImmutableVector, OwnedVector};
+
+#[allow(non_camel_case_types)]
+pub enum utf8 {
+    /// UTF-8, does not emit or consume a BOM
+    utf8,
+    /// UTF-8, emits a BOM on encoding and consumes one on decoding if present
+    utf8_bom,
+}
+
+impl<T: Iterator<char>> Encoder<T, UTF8Encoder<T>> for utf8 {
+    fn encode(&self, src: T) -> UTF8Encoder<T> {
+        match *self {
+            utf8 => UTF8Encoder{ iter: src, bom: false, buf: [0, ..4], lo: 0, hi: 0 },
+            utf8_bom => UTF8Encoder{ iter: src, bom: true, buf: [0, ..4], lo: 0, hi: 0 }
+        }
+    }
+}
+
+impl<T: Iterator<u8>> Decoder<T, UTF8Decoder<T>> for utf8 {
+    fn decode(&self, src: T) -> UTF8Decoder<T> {
+        match *self {
+            utf8 => UTF8Decoder{ iter: Some(src), bom: false, pending: None },
+            utf8_bom => UTF8Decoder{ iter: Some(src), bom: true, pending: None }
+        }
+    }
+}
+
+pub struct UTF8Encoder<T> {
+    priv iter: T,
+    priv bom: bool,
+    priv buf: [u8, ..4],
+    priv lo: uint,
+    priv hi: uint
+}
+
+impl<T: Iterator<char>> Iterator<u8> for UTF8Encoder<T> {
+    #[inline]
+    fn next(&mut self) -> Option<u8> {
+        use encoding::out_of_range::cond;
+
+        if self.bom {
+            self.buf[0] = 0xEF; self.buf[1] = 0xBB; self.buf[2] = 0xBF;
+            self.lo = 0;
+            self.hi = 3;
+            self.bom = false;
+        }
+        if self.lo == self.hi {
+            loop {
+                let c = self.iter.next();
+                if c.is_none() { return None }
+                let mut c = c.unwrap() as u32;
+                fn is_valid(c: u32) -> bool {
+                    (c < 0xD800 || c > 0xDFFF) && c <= 0x10FFFF
+                }
+                if !is_valid(c) {
+                    match cond.raise_default(c as char, || EncodeAsReplacementChar ) {
+                        EncodeAsReplacementChar => c = ReplacementChar as u32,
+                        EncodeAs(c_) => c = c_ as u32,
+                        SkipOutOfRangeChar => loop,
+                        TruncateEncoding => return None,
+                        FailEncoding => fail!("out-of-range char 0x%x found", c as uint)
+                    }
+                    if !is_valid(c) {
+                        fail!("out-of-range char 0x%x found", c as uint);
+                    }
+                }
+                self.lo = 0;
+                self.hi = if c < 0x80 {
+                    self.buf[0] = c as u8;
+                    1
+                } else if c < 0x800 {
+                    self.buf[0] = 0xC0 | (c >> 6) as u8;
+                    self.buf[1] = 0x80 | (c & 0x3F) as u8;
+                    2
+                } else if c < 0x10000 {
+                    self.buf[0] = 0xE0 | (c >> 12) as u8;
+                    self.buf[1] = 0x80 | ((c >> 6) & 0x3F) as u8;
+                    self.buf[2] = 0x80 | (c & 0x3F) as u8;
+                    3
+                } else {
+                    self.buf[0] = 0xF0 | (c >> 18) as u8;
+                    self.buf[1] = 0x80 | ((c >> 12) & 0x3F) as u8;
+                    self.buf[2] = 0x80 | ((c >> 6) & 0x3F) as u8;
+                    self.buf[3] = 0x80 | (c & 0x3F) as u8;
+                    4
+                };
+                break;
+            }
+        }
+        let r = Some(self.buf[self.lo]);
+        self.lo += 1;
+        r
+    }
+
+    #[inline]
+    fn size_hint(&self) -> (uint, Option<uint>) {
+        // most common will be length, but every char could take up to 4 bytes
+        let (lo, hi) = self.iter.size_hint();
+        let hi = do hi.chain |x| {
+            if x > uint::max_value / 4 { None }
+            else { Some(x*4) }
+        };
+        (lo, hi)
+    }
+}
+
+// a byte that can only appear as the first byte of a UTF-8 sequence
+fn is_lead_byte(b: u8) -> bool {
+    b < 0x80 || (b >= 0xC2 && b <= 0xDF) || (b >= 0xE0 && b <= 0xEF) || (b >= 0xF0 && b <= 0xF4)
+}
+
+pub struct UTF8Decoder<T> {
+    priv iter: Option<T>,
+    priv bom: bool,
+    priv pending: Option<u8>
+}
+
+impl<T: Iterator<u8>> UTF8Decoder<T> {
+    #[inline]
+    fn next_byte(&mut self) -> Option<u8> {
+        if self.pending.is_some() {
+            return Some(self.pending.swap_unwrap());
+        }
+        self.iter.get_mut_ref().next()
+    }
+}
+
+impl<T: Iterator<u8>> Iterator<char> for UTF8Decoder<T> {
+    #[inline]
+    fn next(&mut self) -> Option<char> {
+        use encoding::invalid_byte::cond;
+
+        if self.iter.is_none() { return None; }
+
+        loop {
+            let a = self.next_byte();
+            if a.is_none() { self.iter = None; return None; }
+            let a = a.unwrap();
+
+            let (len, mut c) =
+                if a < 0x80 { (1, a as u32) }
+                else if a >= 0xC2 && a <= 0xDF { (2, (a & 0x1F) as u32) }
+                else if a >= 0xE0 && a <= 0xEF { (3, (a & 0x0F) as u32) }
+                else if a >= 0xF0 && a <= 0xF4 { (4, (a & 0x07) as u32) }
+                else {
+                    match cond.raise_default(Some(~[a]), || DecodeAsReplacementChar) {
+                        DecodeAsReplacementChar => return Some(ReplacementChar),
+                        DecodeAs(c) => return Some(c),
+                        SkipInvalidByte => loop,
+                        TruncateDecoding => return None,
+                        FailDecoding => fail!("invalid byte sequence encountered")
+                    }
+                };
+
+            let mut bytes = ~[a];
+            let mut valid = true;
+            let mut i = 1;
+            while i < len {
+                let b = self.next_byte();
+                if b.is_none() {
+                    self.iter = None;
+                    match cond.raise_default(None, || DecodeAsReplacementChar) {
+                        DecodeAsReplacementChar => return Some(ReplacementChar),
+                        DecodeAs(c) => return Some(c),
+                        SkipInvalidByte => return None,
+                        TruncateDecoding => return None,
+                        FailDecoding => fail!("bytestream terminated unexpectedly")
+                    }
+                }
+                let b = b.unwrap();
+
+                let (lo, hi) = if i == 1 {
+                    match a {
+                        0xE0 => (0xA0u8, 0xBFu8),
+                        0xED => (0x80u8, 0x9Fu8),
+                        0xF0 => (0x90u8, 0xBFu8),
+                        0xF4 => (0x80u8, 0x8Fu8),
+                        _ => (0x80u8, 0xBFu8)
+                    }
+                } else { (0x80u8, 0xBFu8) };
+
+                bytes.push(b);
+                if b < lo || b > hi {
+                    valid = false;
+                    break;
+                }
+                c = (c << 6) | (b & 0x3F) as u32;
+                i += 1;
+            }
+
+            if !valid {
+                let last = *bytes.last();
+                if is_lead_byte(last) {
+                    self.pending = Some(last);
+                }
+                match cond.raise_default(Some(bytes), || DecodeAsReplacementChar) {
+                    DecodeAsReplacementChar => return Some(ReplacementChar),
+                    DecodeAs(c) => return Some(c),
+                    SkipInvalidByte => loop,
+                    TruncateDecoding => return None,
+                    FailDecoding => fail!("invalid byte sequence encountered")
+                }
+            }
+
+            if self.bom {
+                self.bom = false;
+                if c == 0xFEFF { loop; }
+            }
+
+            return Some(c as char);
+        }
+    }
+
+    #[inline]
+    fn size_hint(&self) -> (uint, Option<uint>) {
+        // at least one char per 4 bytes, at most one char per byte
+        let (lo, hi) = self.iter.map_default((0, None), |it| it.size_hint());
+        let lo = if lo == uint::max_value { uint::max_value } else { lo / 4 };
+        (lo, hi)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use char::ReplacementChar;
+    use iterator::IteratorUtil;
+
+    #[test]
+    fn test_utf8_encode() {
+        let a = ['t', 'e', 's', 't'];
+        assert_eq!(a.encode_as(utf8).collect::<~[u8]>(),
+                   ~['t' as u8, 'e' as u8, 's' as u8, 't' as u8]);
+
+        let b = ['测', '试'];
+        assert_eq!(b.encode_as(utf8).collect::<~[u8]>(),
+                   ~[0xE6, 0xB5, 0x8B, 0xE8, 0xAF, 0x95]);
+
+        let c = ['𡸳'];
+        assert_eq!(c.encode_as(utf8).collect::<~[u8]>(),
+                   ~[0xF0, 0xA1, 0xB8, 0xB3]);
+    }
+
+    #[test]
+    fn test_utf8_encode_bom() {
+        let a = ['t', 'e', 's', 't'];
+        assert_eq!(a.encode_as(utf8_bom).collect::<~[u8]>(),
+                   ~[0xEF, 0xBB, 0xBF, 't' as u8, 'e' as u8, 's' as u8, 't' as u8]);
+    }
+
+    #[test]
+    fn test_utf8_decode() {
+        let a = ['t' as u8, 'e' as u8, 's' as u8, 't' as u8];
+        assert_eq!(a.decode_as(utf8).collect::<~[char]>(),
+                   ~['t', 'e', 's', 't']);
+
+        let b = [0xE6u8, 0xB5u8, 0x8Bu8, 0xE8u8, 0xAFu8, 0x95u8];
+        assert_eq!(b.decode_as(utf8).collect::<~[char]>(),
+                   ~['测', '试']);
+
+        let c = [0xF0u8, 0xA1u8, 0xB8u8, 0xB3u8];
+        assert_eq!(c.decode_as(utf8).collect::<~[char]>(),
+                   ~['𡸳']);
+    }
+
+    #[test]
+    fn test_utf8_decode_bom() {
+        let a = [0xEFu8, 0xBBu8, 0xBFu8, 't' as u8, 'e' as u8, 's' as u8, 't' as u8];
+        assert_eq!(a.decode_as(utf8_bom).collect::<~[char]>(),
+                   ~['t', 'e', 's', 't']);
+
+        let b = ['t' as u8, 'e' as u8, 's' as u8, 't' as u8];
+        assert_eq!(b.decode_as(utf8_bom).collect::<~[char]>(),
+                   ~['t', 'e', 's', 't']);
+    }
+
+    #[test]
+    fn test_utf8_decode_invalid_lead() {
+        let a = [0xFFu8, 't' as u8];
+        assert_eq!(a.decode_as(utf8).collect::<~[char]>(),
+                   ~[ReplacementChar, 't']);
+    }
+
+    #[test]
+    fn test_utf8_decode_invalid_continuation_preserves_lead() {
+        // 0xE0 starting a 3-byte sequence, followed by an invalid continuation
+        // byte that is itself a valid 1-byte lead; it must not be swallowed.
+        let a = [0xE0u8, 't' as u8];
+        assert_eq!(a.decode_as(utf8).collect::<~[char]>(),
+                   ~[ReplacementChar, 't']);
+    }
+}